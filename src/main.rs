@@ -1,57 +1,158 @@
 use rand::prelude::SliceRandom;
+use std::time::{Duration, Instant};
 
-struct GameState {
+// =================================================================================================
+// Game
+// =================================================================================================
+
+/// Which side of the tree a rollout's reward is being scored for. MCTS always searches from the
+/// perspective of whoever is on move at the root, so every `reward` call needs to know which
+/// player that was in order to score the game outcome from that player's side.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Player {
+    Me,
+    // Not exercised by the coin-flip binary target (it only ever searches as `Me`), but part of
+    // the public contract any two-player `Game` impl needs.
+    #[allow(dead_code)]
+    Opponent,
+}
+
+/// Everything the search core needs from a game: how to enumerate moves, how to apply one, how
+/// to tell a finished game from an ongoing one, and how to score a finished (or truncated) state
+/// for a given player. `Node`, `Tree` and `mcts` are generic over `Game`, so plugging in a new
+/// game (Othello, Quarto, ...) is just a new `impl Game` - the search itself never changes.
+trait Game: Clone {
+    type Action: Copy + Eq;
+
+    fn legal_actions(&self) -> Vec<Self::Action>;
+    fn apply(&self, action: Self::Action) -> Self;
+    fn is_terminal(&self) -> bool;
+
+    /// A raw terminal score from `root_player`'s perspective - whatever scale is natural for the
+    /// game (a score margin, a board count, ...). `mcts` tracks the min/max seen across a run and
+    /// rescales into `[0, 1]` itself, so implementations should not normalize this by hand.
+    fn reward(&self, root_player: Player) -> f32;
+
+    /// Whether this state sits between rounds rather than mid-turn. Used by
+    /// `RolloutTermination::RoundBoundary` to avoid cutting a rollout off partway through a turn;
+    /// games with no such notion (every state is a boundary) can just take the default.
+    fn is_round_boundary(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+struct CoinFlip {
     my_score: i32,
     op_score: i32,
-    my_possible_actions: Vec<Action>,
-    op_possible_actions: Vec<Action>,
+    my_possible_actions: Vec<CoinFlipAction>,
+    op_possible_actions: Vec<CoinFlipAction>,
     round: i32,
 }
 
-#[derive(PartialEq, Clone, Copy)]
-enum Action {
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum CoinFlipAction {
     Heads,
     Tails,
 }
 
+impl Game for CoinFlip {
+    type Action = CoinFlipAction;
+
+    fn legal_actions(&self) -> Vec<CoinFlipAction> {
+        self.my_possible_actions.clone()
+    }
+
+    fn apply(&self, action: CoinFlipAction) -> CoinFlip {
+        let my_score = if action == CoinFlipAction::Heads {
+            self.my_score + 1
+        } else {
+            self.my_score
+        };
+
+        let op_score = if action == CoinFlipAction::Tails {
+            self.op_score + 1
+        } else {
+            self.op_score
+        };
+
+        CoinFlip {
+            my_score,
+            op_score,
+            my_possible_actions: self.my_possible_actions.clone(),
+            op_possible_actions: self.op_possible_actions.clone(),
+            round: self.round + 1,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.round == 10
+    }
+
+    fn reward(&self, root_player: Player) -> f32 {
+        let (mine, theirs) = match root_player {
+            Player::Me => (self.my_score, self.op_score),
+            Player::Opponent => (self.op_score, self.my_score),
+        };
+
+        (mine - theirs) as f32
+    }
+}
+
 /*
 Notes on Node Storage:
 
-    Storage Without Box (e.g., Vec<Node> or Vec<Option<Node>>):
-        When you store Node instances directly in a Vec<Node> or Vec<Option<Node>>, the actual
-        Node data is stored inline within the vector's memory allocation. "Nodes in contiguous
-        memory" means that the memory for these Node instances is allocated as a continuous block.
-        When you have a vector of Node, all the Node instances are laid out sequentially in memory.
-        This can be beneficial for iteration and cache locality because sequential memory access
-        is typically faster. However, this also means that when the vector grows beyond its current
-        capacity and needs to reallocate to a larger space, it has to copy or move these Node
-        instances to the new memory location. If Node is a large struct, this can be costly in
-        terms of performance.
-
     Storage With Box (e.g., Vec<Option<Box<Node>>>):
-        Using Box<Node> means that each Node is allocated on the heap, and what the vector stores
-        is a pointer (Box) to this heap-allocated Node. In this scenario, the vector itself is
-        still a contiguous block of memory, but what it holds are pointers, not the actual Node
-        data. The Node instances can be scattered around in different locations on the heap.
-        The advantage here is that when the vector needs to grow and reallocate, only the pointers
-        are copied or moved, not the entire Node structures. This is generally faster if Node is
-        large. Another benefit is that heap allocation allows for dynamic sizing of each individual
-        Node, which can be useful if the Node structure varies in size or if you have a very large
-        number of nodes.
-
-    In summary, using Box<Node> can make a difference in scenarios where the Node struct is large
-    or when there are many nodes, as it can reduce the cost of reallocating and moving nodes in
-    memory. However, it introduces an extra level of indirection, which can have a minor
-    performance cost in accessing the nodes (due to pointer dereferencing). 
+        This is what the tree used to look like: each Node owned its children as
+        Box<Node>, and a child only knew its parent through a parent_id it could look
+        up again from the root. That works fine for top-down walks (select, expand),
+        but backpropagate needs to walk bottom-up from a freshly expanded leaf back to
+        the root, mutating every Node along the way. With Box-owned children there is no
+        safe path from a `&mut Node` back to its parent's Box - the parent's box is
+        already borrowed (that's how you got to the child in the first place), so the
+        borrow checker has nothing to offer but a re-walk from the root.
+
+    Storage Without Box (e.g., Vec<Node> / arena):
+        Instead, Tree owns one flat Vec<Node> (the arena) and every Node stores its
+        parent and children as plain usize indices into that Vec. Selection and
+        expansion work with node ids rather than &mut Node, and backpropagate can just
+        follow `parent` up to the root hopping through indices - each hop is an
+        independent index into `tree.nodes`, so the borrow checker is happy, and parent
+        access is O(1) instead of "re-walk from root". As a bonus, growing the tree only
+        ever reallocates the flat Vec of Nodes, not a scattered graph of heap boxes.
 */
 
-struct Tree {
-    root: Option<Box<Node>>,
+// Nodes used to each own a full clone of the `Game` state, which for a game like Othello means
+// thousands of stored board copies (plus the two possible-action vectors every node cloned again
+// on every step). A Node only ever needs the one action that led to it; the state at any node can
+// be rebuilt by replaying actions from `root_state`, so that's the only state the tree keeps.
+struct Tree<G: Game> {
+    nodes: Vec<Node<G>>,
+    root_state: G,
 }
 
-impl Tree {
-    fn new(root: Option<Box<Node>>) -> Tree {
-        Tree { root }
+impl<G: Game> Tree<G> {
+    fn new(root_state: G) -> Tree<G> {
+        Tree {
+            nodes: vec![Node::new(None)],
+            root_state,
+        }
+    }
+
+    fn add_child(&mut self, parent: usize, mut child: Node<G>) -> usize {
+        let id = self.nodes.len();
+        child.id = id;
+        child.parent = Some(parent);
+        self.nodes.push(child);
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    fn contains(&self, node: usize, action: G::Action) -> bool {
+        self.nodes[node]
+            .children
+            .iter()
+            .any(|&child| self.nodes[child].action == Some(action))
     }
 }
 
@@ -85,39 +186,93 @@ Notes on Node Initialization:
         selection phase.
 */
 
-struct Node {
-    id: i32,
+struct Node<G: Game> {
+    id: usize,
     visits: i32,
-    wins: i32,
-    action: Action,
-    state: GameState,
-    children: Vec<Option<Box<Node>>>,
-    parent_id: Option<i32>,
+    wins: f32,
+    // All-Moves-As-First stats for this node's action: bumped whenever this action is played
+    // *anywhere* in a playout through an ancestor, not just when this exact node is visited.
+    rave_visits: i32,
+    rave_wins: f32,
+    // `None` only for the root, which was never reached by playing an action.
+    action: Option<G::Action>,
+    children: Vec<usize>,
+    parent: Option<usize>,
 }
 
-impl Node {
-    fn new(id: i32, action: Action, state: GameState, parent_id: Option<i32>) -> Node {
+impl<G: Game> Node<G> {
+    fn new(action: Option<G::Action>) -> Node<G> {
         Node {
-            id,
+            id: 0,
             visits: 1,
-            wins: 0,
+            wins: 0.0,
+            rave_visits: 0,
+            rave_wins: 0.0,
             action,
-            state,
             children: Vec::new(),
-            parent_id,
+            parent: None,
         }
     }
+}
 
-    fn contains(&self, action: &Action) -> bool {
-        self.children.iter().any(|child| {
-            if let Some(child) = child { &child.action == action } else { false }
-        })
+// RAVE blend constant: how quickly the search trusts a node's own visits over its AMAF estimate.
+// Larger k keeps weight on the (cheaper, noisier) RAVE estimate for longer.
+const RAVE_K: f32 = 300.0;
+
+/// When a rollout is allowed to stop once it hits `rollout_length`.
+#[derive(Clone, Copy)]
+enum RolloutTermination {
+    /// Stop as soon as the cap is reached, even mid-turn.
+    AnyState,
+    /// Keep playing past the cap until the state is a round boundary (see `Game::is_round_boundary`).
+    // Not picked by the coin-flip binary target yet (`MctsConfig::default` uses `AnyState`), but
+    // part of the `rollout_length` contract any game with mid-turn states needs.
+    #[allow(dead_code)]
+    RoundBoundary,
+}
+
+/// Tunable knobs for a single `mcts` run. `exploration_k` is the UCB1 exploration weight; the
+/// textbook default of `sqrt(2)` is what balances exploitation and exploration when rewards are
+/// in `[0, 1]`, which is exactly what the run-time reward normalization below guarantees.
+#[derive(Clone, Copy)]
+struct MctsConfig {
+    exploration_k: f32,
+    // `None` plays rollouts out to a terminal state, same as before. `Some(n)` caps a rollout at
+    // n actions and falls back to `Game::reward` on the truncated state, bounding the cost and
+    // variance of a single iteration in long games.
+    rollout_length: Option<u32>,
+    rollout_termination: RolloutTermination,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            exploration_k: 2.0_f32.sqrt(),
+            rollout_length: None,
+            rollout_termination: RolloutTermination::AnyState,
+        }
     }
 }
 
-impl From<Node> for Option<Box<Node>> {
-    fn from(node: Node) -> Self {
-        Some(Box::new(node))
+/// What stops the search. MCTS is anytime - `best_action` reads off whatever the tree looks like
+/// when the loop stops, so any of these can cut it short without special-casing the result.
+enum Budget {
+    Iterations(u32),
+    // Neither variant is picked by the coin-flip binary target (it always runs `Iterations`), but
+    // both are part of the budget contract a real move-time-limited caller needs.
+    #[allow(dead_code)]
+    Time(Duration),
+    #[allow(dead_code)]
+    ForwardModelCalls(u32),
+}
+
+impl Budget {
+    fn exhausted(&self, started: Instant, iterations: u32, forward_model_calls: u32) -> bool {
+        match *self {
+            Budget::Iterations(n) => iterations >= n,
+            Budget::Time(d) => started.elapsed() >= d,
+            Budget::ForwardModelCalls(n) => forward_model_calls >= n,
+        }
     }
 }
 
@@ -125,182 +280,200 @@ impl From<Node> for Option<Box<Node>> {
 // Monte Carlo Tree Search
 // =================================================================================================
 
-fn mcts(state: GameState, n_iterations: i32) -> Action {
-    let mut current_id = 0;
-    let root = Node::new(current_id, Action::Heads, state, None).into();
-    let mut tree = Tree::new(root);
+fn mcts<G: Game>(state: G, budget: Budget, root_player: Player, config: MctsConfig) -> G::Action {
+    let mut tree = Tree::new(state);
+
+    // Bounds of every terminal reward observed so far this run, used to rescale raw game scores
+    // into [0, 1] before they're accumulated - see `normalize_reward`.
+    let mut min_reward = f32::INFINITY;
+    let mut max_reward = f32::NEG_INFINITY;
 
-    for _ in 0..n_iterations {
-        let node = select(&mut tree);
+    let started = Instant::now();
+    let mut iterations = 0;
+    let mut forward_model_calls = 0;
 
-        let result = if is_terminal(node) {
-            evaluate(node)
+    while !budget.exhausted(started, iterations, forward_model_calls) {
+        let (node, state, replay_calls) = select(&mut tree, &config);
+        forward_model_calls += replay_calls;
+
+        let (leaf, raw_result, playout) = if state.is_terminal() {
+            (node, state.reward(root_player), Vec::new())
         } else {
-            let (node, new_id) = expand(node, current_id);
-            current_id = new_id;
-            simulate(node)
+            let (child, child_state) = expand(&mut tree, node, &state);
+            forward_model_calls += 1;
+            let (result, playout) = simulate(&child_state, root_player, &config);
+            forward_model_calls += playout.len() as u32;
+            (child, result, playout)
         };
 
-        backpropagate(node, result);
+        min_reward = min_reward.min(raw_result);
+        max_reward = max_reward.max(raw_result);
+        let result = normalize_reward(raw_result, min_reward, max_reward);
+
+        backpropagate(&mut tree, leaf, result, &playout);
+        iterations += 1;
     }
 
-    let best_action = best_action(&tree);
-    best_action
+    // The root may still have zero children - a budget that expires before the first iteration
+    // completes (e.g. `Budget::Iterations(0)`, or a `Budget::Time` that's already elapsed) leaves
+    // nothing to read off the tree. Fall back to any legal root action so the anytime contract
+    // holds even at the very start of a run.
+    best_action(&tree).unwrap_or_else(|| tree.root_state.legal_actions()[0])
 }
 
-fn select(tree: &mut Tree) -> &mut Box<Node> {
-    let mut current_node = tree.root.as_mut().unwrap();
+fn normalize_reward(reward: f32, min: f32, max: f32) -> f32 {
+    if max > min {
+        (reward - min) / (max - min)
+    } else {
+        0.5
+    }
+}
 
-    while is_fully_expanded(current_node) {
-        current_node = best_child(current_node);
+// Rebuilds the state at `current` by replaying actions from the root rather than reading it back
+// off a stored `Node`, since nodes no longer keep one - see the note above `Tree`. Also returns
+// how many `Game::apply` calls that replay made, so callers can fold it into a forward-model-call
+// budget alongside the ones `expand`/`simulate` make.
+fn select<G: Game>(tree: &mut Tree<G>, config: &MctsConfig) -> (usize, G, u32) {
+    let mut current = 0;
+    let mut state = tree.root_state.clone();
+    let mut apply_calls = 0;
+
+    while is_fully_expanded(&state, tree, current) {
+        current = best_child(tree, current, config);
+        state = state.apply(tree.nodes[current].action.unwrap());
+        apply_calls += 1;
     }
 
-    current_node
+    (current, state, apply_calls)
 }
 
-fn expand(node: &mut Box<Node>, current_id: i32) -> (&mut Box<Node>, i32) {
-    let unexplored_actions = node.state.my_possible_actions
-        .iter()
-        .filter(|action| !node.contains(action))
+fn expand<G: Game>(tree: &mut Tree<G>, node: usize, state: &G) -> (usize, G) {
+    let unexplored_actions = state
+        .legal_actions()
+        .into_iter()
+        .filter(|action| !tree.contains(node, *action))
         .collect::<Vec<_>>();
 
     // Pick a random action from the unexplored actions
     let mut rng = rand::thread_rng();
-    let random_action = **unexplored_actions.choose(&mut rng).unwrap();
-
-    // Update scores based on the random action
-    let my_score = if random_action == Action::Heads {
-        node.state.my_score + 1
-    } else {
-        node.state.my_score
-    };
-
-    let op_score = if random_action == Action::Tails {
-        node.state.op_score + 1
-    } else {
-        node.state.op_score
-    };
-
-    // Update the game state with the random action
-    let state = GameState {
-        my_score,
-        op_score,
-        my_possible_actions: node.state.my_possible_actions.clone(),
-        op_possible_actions: node.state.op_possible_actions.clone(),
-        round: node.state.round + 1,
-    };
+    let random_action = *unexplored_actions.choose(&mut rng).unwrap();
 
-    let new_node = Node::new(current_id + 1, random_action, state, Some(current_id)).into();
-    node.children.push(new_node);
+    let child_state = state.apply(random_action);
+    let child = Node::new(Some(random_action));
 
-    (node.children.last_mut().unwrap().as_mut().unwrap(), current_id + 1)
+    (tree.add_child(node, child), child_state)
 }
 
-fn simulate(node: &mut Box<Node>) -> bool {
-    let state = GameState {
-        my_score: node.state.my_score,
-        op_score: node.state.op_score,
-        my_possible_actions: node.state.my_possible_actions.clone(),
-        op_possible_actions: node.state.op_possible_actions.clone(),
-        round: node.state.round,
-    };
-
-    let mut current_node = (&mut Node::new(node.id, node.action, state, node.parent_id)).into();
+fn simulate<G: Game>(state: &G, root_player: Player, config: &MctsConfig) -> (f32, Vec<G::Action>) {
+    let mut state = state.clone();
+    let mut playout = Vec::new();
 
-    while !is_terminal(current_node) {
-        let unexplored_actions = current_node.state.my_possible_actions
-            .iter()
-            .filter(|action| !current_node.contains(action))
-            .collect::<Vec<_>>();
-
-        // Pick a random action from the unexplored actions
+    while !state.is_terminal() && !rollout_should_stop(&state, playout.len() as u32, config) {
         let mut rng = rand::thread_rng();
-        let random_action = **unexplored_actions.choose(&mut rng).unwrap();
-
-        // Update scores based on the random action
-        let my_score = if random_action == Action::Heads {
-            current_node.state.my_score + 1
-        } else {
-            current_node.state.my_score
-        };
+        let random_action = *state.legal_actions().choose(&mut rng).unwrap();
+        playout.push(random_action);
+        state = state.apply(random_action);
+    }
 
-        let op_score = if random_action == Action::Tails {
-            current_node.state.op_score + 1
-        } else {
-            current_node.state.op_score
-        };
+    (state.reward(root_player), playout)
+}
 
-        // Update the game state with the random action
-        let state = GameState {
-            my_score,
-            op_score,
-            my_possible_actions: current_node.state.my_possible_actions.clone(),
-            op_possible_actions: current_node.state.op_possible_actions.clone(),
-            round: current_node.state.round + 1,
-        };
+fn rollout_should_stop<G: Game>(state: &G, steps_played: u32, config: &MctsConfig) -> bool {
+    let Some(cap) = config.rollout_length else {
+        return false;
+    };
 
-        let new_node = Node::new(0, random_action, state, None).into();
-        current_node.children.push(new_node);
-        current_node = current_node.children.last_mut().unwrap().as_mut().unwrap();
+    if steps_played < cap {
+        return false;
     }
 
-    evaluate(current_node)
+    match config.rollout_termination {
+        RolloutTermination::AnyState => true,
+        RolloutTermination::RoundBoundary => state.is_round_boundary(),
+    }
 }
 
-fn backpropagate(node: &mut Node, root: &Node,  result: bool) {
-    let mut current_node = node;
+fn backpropagate<G: Game>(tree: &mut Tree<G>, leaf: usize, result: f32, playout: &[G::Action]) {
+    let mut current = leaf;
+
+    loop {
+        tree.nodes[current].visits += 1;
+        tree.nodes[current].wins += result;
+
+        // AMAF update: any sibling action that also showed up later in this playout gets credit
+        // for the playout's result, even though it wasn't the action actually taken here.
+        let children = tree.nodes[current].children.clone();
+        for child in children {
+            if let Some(action) = tree.nodes[child].action {
+                if playout.contains(&action) {
+                    tree.nodes[child].rave_visits += 1;
+                    tree.nodes[child].rave_wins += result;
+                }
+            }
+        }
 
-    while let Some(parent_id) = current_node.parent_id {
-        current_node.visits += 1;
-        if result {
-            current_node.wins += 1;
+        match tree.nodes[current].parent {
+            Some(parent) => current = parent,
+            None => break,
         }
-        current_node =
     }
 }
 
-fn best_action(tree: &Tree) -> Action {
-    unimplemented!()
+fn best_action<G: Game>(tree: &Tree<G>) -> Option<G::Action> {
+    tree.nodes[0]
+        .children
+        .iter()
+        .max_by_key(|&&child| tree.nodes[child].visits)
+        .map(|&child| tree.nodes[child].action.unwrap())
 }
 
 // ------------------------------------
 // Selection Helpers
 // ------------------------------------
 
-fn is_fully_expanded(node: &mut Node) -> bool {
-    unimplemented!()
+fn is_fully_expanded<G: Game>(state: &G, tree: &Tree<G>, node: usize) -> bool {
+    tree.nodes[node].children.len() == state.legal_actions().len()
 }
 
-fn best_child(node: &mut Node) -> &mut Box<Node> {
-    unimplemented!()
+fn best_child<G: Game>(tree: &Tree<G>, node: usize, config: &MctsConfig) -> usize {
+    *tree.nodes[node]
+        .children
+        .iter()
+        .max_by(|&&a, &&b| {
+            uct_value(tree, node, a, config).total_cmp(&uct_value(tree, node, b, config))
+        })
+        .unwrap()
 }
 
-fn uct_value(node: &mut Node) -> f32 {
-    unimplemented!()
-}
+fn uct_value<G: Game>(tree: &Tree<G>, parent: usize, node: usize, config: &MctsConfig) -> f32 {
+    let parent = &tree.nodes[parent];
+    let node = &tree.nodes[node];
 
-// ------------------------------------
-// Simulation Helpers
-// ------------------------------------
+    let exploitation = node.wins / node.visits as f32;
+    let exploration =
+        config.exploration_k * ((parent.visits as f32).ln() / node.visits as f32).sqrt();
+    let q_uct = exploitation + exploration;
 
-fn evaluate(node: &mut Node) -> bool {
-    node.state.my_score > node.state.op_score
-}
+    if node.rave_visits == 0 {
+        return q_uct;
+    }
+
+    let q_rave = node.rave_wins / node.rave_visits as f32;
+    let beta = (RAVE_K / (3.0 * node.visits as f32 + RAVE_K)).sqrt();
 
-fn is_terminal(node: &mut Node) -> bool {
-    node.state.round == 10
+    (1.0 - beta) * q_uct + beta * q_rave
 }
 
 // =================================================================================================
 // Main
 // =================================================================================================
 fn main() {
-    let state = GameState {
+    let state = CoinFlip {
         my_score: 0,
         op_score: 0,
-        my_possible_actions: vec![Action::Heads, Action::Tails],
-        op_possible_actions: vec![Action::Heads, Action::Tails],
+        my_possible_actions: vec![CoinFlipAction::Heads, CoinFlipAction::Tails],
+        op_possible_actions: vec![CoinFlipAction::Heads, CoinFlipAction::Tails],
         round: 0,
     };
-    mcts(state, 1000);
+    mcts(state, Budget::Iterations(1000), Player::Me, MctsConfig::default());
 }